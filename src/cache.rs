@@ -1,6 +1,14 @@
-use dashmap::{mapref::one::Ref, DashMap};
+use std::time::{Duration, Instant};
+
+use dashmap::{
+    mapref::{multiple::RefMulti, one::Ref},
+    DashMap,
+};
 use thiserror::Error;
-use twilight_http::{request::channel::webhook::CreateWebhook, Client};
+use twilight_http::{
+    request::{channel::webhook::CreateWebhook, AuditLogReason},
+    Client,
+};
 use twilight_model::{
     channel::Webhook,
     gateway::event::Event,
@@ -24,9 +32,39 @@ pub enum Error {
     Validation(#[from] twilight_validate::request::ValidationError),
 }
 
-/// Cache to hold webhooks, keyed by channel IDs for general usage
+/// Optional capacity and time-to-live bounds on the cache
+#[derive(Debug, Clone, Copy)]
+struct Config {
+    /// Maximum number of cached webhooks before the least-recently-used ones
+    /// are evicted
+    capacity: usize,
+    /// How long an entry stays fresh before [`Cache::get`] and
+    /// [`Cache::get_infallible`] treat it as stale
+    ttl: Duration,
+}
+
+/// Bookkeeping for a cached entry, used for TTL expiry and least-recently-used
+/// eviction
+#[derive(Debug, Clone, Copy)]
+struct Metadata {
+    /// When the entry was last validated against the API
+    validated_at: Instant,
+    /// When the entry was last read from the cache
+    used_at: Instant,
+}
+
+/// Cache to hold webhooks, keyed by channel ID and webhook name so a channel
+/// can hold several bot-owned webhooks used for different purposes
 #[derive(Debug)]
-pub struct Cache(DashMap<Id<ChannelMarker>, Webhook>);
+pub struct Cache {
+    /// The cached webhooks
+    webhooks: DashMap<(Id<ChannelMarker>, String), Webhook>,
+    /// Per-entry bookkeeping, only populated when a [`Config`] is set
+    metadata: DashMap<(Id<ChannelMarker>, String), Metadata>,
+    /// Capacity and time-to-live bounds, or `None` for an unbounded,
+    /// never-expiring cache
+    config: Option<Config>,
+}
 
 impl Default for Cache {
     fn default() -> Self {
@@ -35,7 +73,7 @@ impl Default for Cache {
 }
 
 impl Cache {
-    /// Creates a new webhook cache
+    /// Creates a new unbounded, never-expiring webhook cache
     ///
     /// # Invalidation warning
     /// Make sure you receive `ChannelDelete` and `GuildDelete` events and call
@@ -46,7 +84,84 @@ impl Cache {
     /// events to remove manually deleted webhooks from the cache
     #[must_use]
     pub fn new() -> Self {
-        Self(DashMap::new())
+        Self {
+            webhooks: DashMap::new(),
+            metadata: DashMap::new(),
+            config: None,
+        }
+    }
+
+    /// Creates a new webhook cache that holds at most `capacity` webhooks,
+    /// evicting the least-recently-used entries past that, and treats entries
+    /// older than `ttl` as stale so they're lazily re-fetched
+    ///
+    /// Unlike [`Cache::new`], stale entries are refreshed on access, so calling
+    /// [`Cache::validate`] on every `WebhookUpdate` event isn't required
+    ///
+    /// # Invalidation warning
+    /// You should still receive `ChannelDelete` and `GuildDelete` events and
+    /// call [`Cache::update`] on them to promptly remove inaccessible webhooks
+    #[must_use]
+    pub fn with_config(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            webhooks: DashMap::new(),
+            metadata: DashMap::new(),
+            config: Some(Config { capacity, ttl }),
+        }
+    }
+
+    /// Returns whether the entry for `key` is older than the configured TTL
+    fn is_stale(&self, key: &(Id<ChannelMarker>, String)) -> bool {
+        match (self.config, self.metadata.get(key)) {
+            (Some(config), Some(metadata)) => metadata.validated_at.elapsed() >= config.ttl,
+            _ => false,
+        }
+    }
+
+    /// Marks the entry for `key` as just used, for least-recently-used eviction
+    fn touch(&self, key: &(Id<ChannelMarker>, String)) {
+        if let Some(mut metadata) = self.metadata.get_mut(key) {
+            metadata.used_at = Instant::now();
+        }
+    }
+
+    /// Records a freshly validated and used entry for `key`
+    fn record(&self, key: (Id<ChannelMarker>, String)) {
+        if self.config.is_some() {
+            let now = Instant::now();
+            self.metadata.insert(
+                key,
+                Metadata {
+                    validated_at: now,
+                    used_at: now,
+                },
+            );
+        }
+    }
+
+    /// Evicts least-recently-used entries until the cache is within its
+    /// configured capacity, never evicting `exempt` (the entry just inserted)
+    fn evict(&self, exempt: &(Id<ChannelMarker>, String)) {
+        let capacity = match self.config {
+            Some(config) => config.capacity,
+            None => return,
+        };
+
+        while self.webhooks.len() > capacity {
+            let lru = self
+                .metadata
+                .iter()
+                .filter(|entry| entry.key() != exempt)
+                .min_by_key(|entry| entry.used_at)
+                .map(|entry| entry.key().clone());
+            match lru {
+                Some(key) => {
+                    self.webhooks.remove(&key);
+                    self.metadata.remove(&key);
+                }
+                None => break,
+            }
+        }
     }
 
     /// Convenience function to get from the cache, requesting it from the API
@@ -55,9 +170,13 @@ impl Cache {
     /// # Required permissions
     /// Make sure the bot has `MANAGE_WEBHOOKS` permission in the given channel
     ///
+    /// # Audit log
+    /// If the webhook has to be created, `reason` is set as the audit-log
+    /// reason so the guild audit log explains why it appeared
+    ///
     /// # Errors
     /// Returns an [`Error::Http`] or [`Error::Deserialize`] if the webhook
-    /// isn't in the cache
+    /// isn't in the cache, or [`Error::Validation`] if `reason` is invalid
     ///
     /// # Panics
     /// If the webhook that was just inserted to the cache somehow doesn't exist
@@ -67,8 +186,9 @@ impl Cache {
         http: &Client,
         channel_id: Id<ChannelMarker>,
         name: &str,
-    ) -> Result<Ref<'_, Id<ChannelMarker>, Webhook>, Error> {
-        if let Some(webhook) = self.get(channel_id) {
+        reason: Option<&str>,
+    ) -> Result<Ref<'_, (Id<ChannelMarker>, String), Webhook>, Error> {
+        if let Some(webhook) = self.get(channel_id, name) {
             Ok(webhook)
         } else {
             let webhook = if let Some(webhook) = http
@@ -78,40 +198,89 @@ impl Cache {
                 .models()
                 .await?
                 .into_iter()
-                .find(|w| w.token.is_some())
+                .find(|w| w.token.is_some() && w.name.as_deref() == Some(name))
             {
                 webhook
             } else {
-                http.create_webhook(channel_id, name)?
-                    .exec()
-                    .await?
-                    .model()
-                    .await?
+                let create_webhook = http.create_webhook(channel_id, name)?;
+                let create_webhook = match reason {
+                    Some(reason) => create_webhook.reason(reason)?,
+                    None => create_webhook,
+                };
+                create_webhook.exec().await?.model().await?
             };
-            self.0.insert(channel_id, webhook);
-            Ok(self.get(channel_id).unwrap())
+            let key = (channel_id, name.to_owned());
+            self.webhooks.insert(key.clone(), webhook);
+            self.record(key.clone());
+            self.evict(&key);
+            Ok(self.webhooks.get(&key).unwrap())
         }
     }
 
     /// Creates the passed webhook and caches it, it takes a `CreateWebhook`
     /// instead of a `Webhook` to reduce boilerplate and avoid clones
     ///
+    /// If `reason` is given, it's set as the audit-log reason for the creation
+    ///
     /// # Errors
-    /// Returns [`Error::Http`] or [`Error::Deserialize`]
-    pub async fn create<'a>(&self, create_webhook: CreateWebhook<'a>) -> Result<(), Error> {
+    /// Returns [`Error::Http`] or [`Error::Deserialize`], or
+    /// [`Error::Validation`] if `reason` is invalid
+    pub async fn create<'a>(
+        &self,
+        create_webhook: CreateWebhook<'a>,
+        reason: Option<&str>,
+    ) -> Result<(), Error> {
+        let create_webhook = match reason {
+            Some(reason) => create_webhook.reason(reason)?,
+            None => create_webhook,
+        };
         let webhook = create_webhook.exec().await?.model().await?;
-        self.0.insert(webhook.channel_id, webhook);
+        let key = (webhook.channel_id, webhook.name.clone().unwrap_or_default());
+        self.webhooks.insert(key.clone(), webhook);
+        self.record(key.clone());
+        self.evict(&key);
 
         Ok(())
     }
 
-    /// Returns the webhook for the given `channel_id`, if it exists
+    /// Returns the webhook for the given `channel_id` and `name`, if it exists
+    /// and isn't stale
+    ///
+    /// With a [`Cache::with_config`] TTL, an entry older than the TTL is
+    /// treated as absent so the caller re-fetches it through
+    /// [`Cache::get_infallible`]
     #[must_use]
     pub fn get(
         &self,
         channel_id: Id<ChannelMarker>,
-    ) -> Option<Ref<'_, Id<ChannelMarker>, Webhook>> {
-        self.0.get(&channel_id)
+        name: &str,
+    ) -> Option<Ref<'_, (Id<ChannelMarker>, String), Webhook>> {
+        let key = (channel_id, name.to_owned());
+        if self.is_stale(&key) {
+            self.webhooks.remove(&key);
+            self.metadata.remove(&key);
+            return None;
+        }
+        let webhook = self.webhooks.get(&key)?;
+        self.touch(&key);
+        Some(webhook)
+    }
+
+    /// Returns a non-stale cached webhook for the given `channel_id`, if any
+    /// exist, preserving the single-webhook-per-channel ergonomics
+    ///
+    /// Which webhook is returned when the channel has several is not defined
+    #[must_use]
+    pub fn first(
+        &self,
+        channel_id: Id<ChannelMarker>,
+    ) -> Option<RefMulti<'_, (Id<ChannelMarker>, String), Webhook>> {
+        let webhook = self
+            .webhooks
+            .iter()
+            .find(|entry| entry.key().0 == channel_id && !self.is_stale(entry.key()))?;
+        self.touch(webhook.key());
+        Some(webhook)
     }
 
     /// Validates the cache by retrieving the webhooks from the API
@@ -135,20 +304,35 @@ impl Cache {
         http: &Client,
         channel_id: Id<ChannelMarker>,
     ) -> Result<(), Error> {
-        if !self.0.contains_key(&channel_id) {
+        if !self.webhooks.iter().any(|entry| entry.key().0 == channel_id) {
             return Ok(());
         }
 
-        if !http
+        let webhooks = http
             .channel_webhooks(channel_id)
             .exec()
             .await?
             .models()
-            .await?
-            .iter()
-            .any(|webhook| webhook.token.is_some())
-        {
-            self.0.remove(&channel_id);
+            .await?;
+
+        self.webhooks.retain(|(cached_channel_id, name), _| {
+            if *cached_channel_id != channel_id {
+                return true;
+            }
+            let valid = webhooks
+                .iter()
+                .any(|webhook| webhook.token.is_some() && webhook.name.as_deref() == Some(name));
+            if !valid {
+                self.metadata.remove(&(*cached_channel_id, name.clone()));
+            }
+            valid
+        });
+
+        let now = Instant::now();
+        for mut entry in self.metadata.iter_mut() {
+            if entry.key().0 == channel_id {
+                entry.validated_at = now;
+            }
         }
 
         Ok(())
@@ -160,11 +344,20 @@ impl Cache {
     pub fn update(&self, event: &Event) {
         match event {
             Event::ChannelDelete(channel) => {
-                self.0.remove(&channel.id);
+                self.webhooks
+                    .retain(|(channel_id, _), _| *channel_id != channel.id);
+                self.metadata
+                    .retain(|(channel_id, _), _| *channel_id != channel.id);
+            }
+            Event::GuildDelete(guild) => {
+                for entry in self.webhooks.iter() {
+                    if entry.guild_id == Some(guild.id) {
+                        self.metadata.remove(entry.key());
+                    }
+                }
+                self.webhooks
+                    .retain(|_, webhook| webhook.guild_id != Some(guild.id));
             }
-            Event::GuildDelete(guild) => self
-                .0
-                .retain(|_, webhook| webhook.guild_id != Some(guild.id)),
             _ => (),
         };
     }
@@ -172,6 +365,8 @@ impl Cache {
 
 #[cfg(test)]
 mod tests {
+    use std::time::{Duration, Instant};
+
     use twilight_model::{
         channel::{Channel, ChannelType, Webhook, WebhookType},
         gateway::{
@@ -181,7 +376,7 @@ mod tests {
         id::Id,
     };
 
-    use crate::cache::Cache;
+    use super::{Cache, Metadata};
 
     const WEBHOOK: Webhook = Webhook {
         id: Id::new(1),
@@ -201,33 +396,34 @@ mod tests {
     #[test]
     fn get() {
         let cache = Cache::new();
-        cache.0.insert(Id::new(1), WEBHOOK);
+        cache.webhooks.insert((Id::new(1), String::new()), WEBHOOK);
 
-        assert!(cache.get(Id::new(2)).is_none());
+        assert!(cache.get(Id::new(2), "").is_none());
 
-        assert_eq!(cache.get(Id::new(1)).as_deref(), Some(&WEBHOOK));
+        assert_eq!(cache.get(Id::new(1), "").as_deref(), Some(&WEBHOOK));
+        assert_eq!(cache.first(Id::new(1)).as_deref(), Some(&WEBHOOK));
     }
 
     #[test]
     fn update() {
         let cache = Cache::new();
-        cache.0.insert(Id::new(1), WEBHOOK);
-        cache.0.insert(Id::new(2), WEBHOOK);
+        cache.webhooks.insert((Id::new(1), String::new()), WEBHOOK);
+        cache.webhooks.insert((Id::new(2), String::new()), WEBHOOK);
 
         cache.update(&Event::GuildDelete(GuildDelete {
             id: Id::new(11),
             unavailable: false,
         }));
-        assert_eq!(cache.get(Id::new(1)).as_deref(), Some(&WEBHOOK));
+        assert_eq!(cache.get(Id::new(1), "").as_deref(), Some(&WEBHOOK));
 
         cache.update(&Event::GuildDelete(GuildDelete {
             id: Id::new(10),
             unavailable: false,
         }));
-        assert!(cache.get(Id::new(1)).is_none());
-        assert!(cache.get(Id::new(2)).is_none());
+        assert!(cache.get(Id::new(1), "").is_none());
+        assert!(cache.get(Id::new(2), "").is_none());
 
-        cache.0.insert(Id::new(3), WEBHOOK);
+        cache.webhooks.insert((Id::new(3), String::new()), WEBHOOK);
         cache.update(&Event::ChannelDelete(Box::new(ChannelDelete(Channel {
             id: Id::new(3),
             guild_id: Some(Id::new(10)),
@@ -257,6 +453,44 @@ mod tests {
             user_limit: None,
             video_quality_mode: None,
         }))));
-        assert!(cache.get(Id::new(3)).is_none());
+        assert!(cache.get(Id::new(3), "").is_none());
+    }
+
+    #[test]
+    fn stale_get() {
+        let cache = Cache::with_config(10, Duration::ZERO);
+        let key = (Id::new(1), String::new());
+        cache.webhooks.insert(key.clone(), WEBHOOK);
+        cache.record(key);
+
+        assert!(cache.get(Id::new(1), "").is_none());
+    }
+
+    #[test]
+    fn eviction() {
+        let cache = Cache::with_config(1, Duration::from_secs(60));
+        let now = Instant::now();
+
+        cache.webhooks.insert((Id::new(1), String::new()), WEBHOOK);
+        cache.metadata.insert(
+            (Id::new(1), String::new()),
+            Metadata {
+                validated_at: now,
+                used_at: now,
+            },
+        );
+        cache.webhooks.insert((Id::new(2), String::new()), WEBHOOK);
+        cache.metadata.insert(
+            (Id::new(2), String::new()),
+            Metadata {
+                validated_at: now,
+                used_at: now + Duration::from_secs(1),
+            },
+        );
+
+        cache.evict(&(Id::new(2), String::new()));
+
+        assert!(cache.get(Id::new(1), "").is_none());
+        assert_eq!(cache.get(Id::new(2), "").as_deref(), Some(&WEBHOOK));
     }
 }
@@ -1,13 +1,30 @@
-use twilight_http::request::channel::webhook::ExecuteWebhook;
-use twilight_model::channel::Channel;
+use twilight_http::{
+    request::channel::webhook::{DeleteWebhookMessage, ExecuteWebhook, UpdateWebhookMessage},
+    Client,
+};
+use twilight_model::{
+    channel::{Channel, ChannelType, Webhook},
+    id::{marker::MessageMarker, Id},
+};
+
+use crate::cache::Error;
 
 /// Utility functions to execute webhooks
-trait ExecuteWebhookExt {
+pub trait ExecuteWebhookExt<'a> {
     /// If the channel is a thread channel, execute the webhook in it
     fn in_channel(self, channel: &Channel) -> Self;
+
+    /// Execute the webhook in the given channel, whether it's a thread or a
+    /// forum channel
+    ///
+    /// If the channel is a thread channel, the webhook is executed in it; if
+    /// it's a `GuildForum` channel, a new thread is created with `thread_name`;
+    /// otherwise the channel is left untouched, so media channels get no new
+    /// thread
+    fn in_channel_or_forum(self, channel: &Channel, thread_name: &'a str) -> Self;
 }
 
-impl ExecuteWebhookExt for ExecuteWebhook<'_> {
+impl<'a> ExecuteWebhookExt<'a> for ExecuteWebhook<'a> {
     fn in_channel(self, channel: &Channel) -> Self {
         if channel.kind.is_thread() {
             self.thread_id(channel.id)
@@ -15,4 +32,90 @@ impl ExecuteWebhookExt for ExecuteWebhook<'_> {
             self
         }
     }
+
+    fn in_channel_or_forum(self, channel: &Channel, thread_name: &'a str) -> Self {
+        match channel.kind {
+            ChannelType::GuildForum => self.thread_name(thread_name),
+            _ => self.in_channel(channel),
+        }
+    }
+}
+
+/// Utility functions to edit or delete messages a cached webhook created
+pub trait WebhookMessageExt {
+    /// Build a request to edit a message the given cached webhook created,
+    /// reading its `id` and `token` for you
+    ///
+    /// Returns `None` if the cached webhook has no token
+    fn update_webhook_message_from<'a>(
+        &'a self,
+        webhook: &'a Webhook,
+        message_id: Id<MessageMarker>,
+    ) -> Option<UpdateWebhookMessage<'a>>;
+
+    /// Build a request to delete a message the given cached webhook created,
+    /// reading its `id` and `token` for you
+    ///
+    /// Returns `None` if the cached webhook has no token
+    fn delete_webhook_message_from<'a>(
+        &'a self,
+        webhook: &'a Webhook,
+        message_id: Id<MessageMarker>,
+    ) -> Option<DeleteWebhookMessage<'a>>;
+}
+
+impl WebhookMessageExt for Client {
+    fn update_webhook_message_from<'a>(
+        &'a self,
+        webhook: &'a Webhook,
+        message_id: Id<MessageMarker>,
+    ) -> Option<UpdateWebhookMessage<'a>> {
+        Some(self.update_webhook_message(webhook.id, webhook.token.as_ref()?, message_id))
+    }
+
+    fn delete_webhook_message_from<'a>(
+        &'a self,
+        webhook: &'a Webhook,
+        message_id: Id<MessageMarker>,
+    ) -> Option<DeleteWebhookMessage<'a>> {
+        Some(self.delete_webhook_message(webhook.id, webhook.token.as_ref()?, message_id))
+    }
+}
+
+/// Unarchives the given thread if Discord has archived or locked it, so a
+/// cached webhook can be executed in it afterwards
+///
+/// Call this before the execute request when posting into threads that may
+/// have been auto-archived (for example scheduled or delayed posts); callers
+/// who don't need the extra request can simply not call it
+///
+/// Does nothing if the channel isn't a thread or isn't archived or locked
+///
+/// # Required permissions
+/// Requires `MANAGE_THREADS` permission if the thread is locked
+///
+/// # Errors
+/// Returns [`Error::Http`]
+pub async fn unarchive_thread(http: &Client, channel: &Channel) -> Result<(), Error> {
+    if let Some(metadata) = &channel.thread_metadata {
+        if metadata.archived || metadata.locked {
+            let mut update_thread = http.update_thread(channel.id).archived(false);
+            if metadata.locked {
+                update_thread = update_thread.locked(false);
+            }
+            update_thread.exec().await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Executes the webhook, forcing `wait(true)` so Discord returns the created
+/// message, and hands back its ID so it can later be edited or deleted without
+/// juggling the webhook token by hand
+///
+/// # Errors
+/// Returns [`Error::Http`] or [`Error::Deserialize`]
+pub async fn execute_returning_id(execute: ExecuteWebhook<'_>) -> Result<Id<MessageMarker>, Error> {
+    Ok(execute.wait().exec().await?.model().await?.id)
 }